@@ -0,0 +1,43 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+const SEMICOLON: u8 = b';';
+
+fn scan_scalar(data: &[u8]) -> usize {
+    let mut count = 0;
+    let mut data = data;
+    while let Some(pos) = data.iter().position(|c| *c == SEMICOLON) {
+        count += 1;
+        data = &data[pos + 1..];
+    }
+    count
+}
+
+fn scan_memchr(data: &[u8]) -> usize {
+    let mut count = 0;
+    let mut data = data;
+    while let Some(pos) = memchr::memchr(SEMICOLON, data) {
+        count += 1;
+        data = &data[pos + 1..];
+    }
+    count
+}
+
+fn sample_data() -> Vec<u8> {
+    let mut data = Vec::new();
+    for i in 0..1_000_000 {
+        data.extend_from_slice(format!("Station{};{}.{}\n", i % 10_000, i % 100, i % 10).as_bytes());
+    }
+    data
+}
+
+fn bench_scan(c: &mut Criterion) {
+    let data = sample_data();
+
+    let mut group = c.benchmark_group("scan_semicolons");
+    group.bench_function("scalar", |b| b.iter(|| scan_scalar(black_box(&data))));
+    group.bench_function("memchr", |b| b.iter(|| scan_memchr(black_box(&data))));
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
@@ -0,0 +1,61 @@
+use std::cell::RefCell;
+
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// A bump allocator that owns station-name bytes for the lifetime of a
+/// worker. Names are copied into it once, on first sighting, and handed
+/// out as borrowed slices, so the hot `StationStats::record` path never
+/// allocates a `String` per station; the arena itself only allocates
+/// when its current block fills up.
+///
+/// Blocks are only ever appended to, never reallocated or mutated once
+/// filled, so a slice handed out of a block stays valid for as long as
+/// the arena itself is alive.
+pub struct Arena {
+    blocks: RefCell<Vec<Vec<u8>>>,
+}
+
+// SAFETY: an `Arena` is only ever mutated (through `alloc`) by one
+// thread at a time: first by the worker thread that built it, then,
+// once that worker has finished and its `StationStats` has come back
+// through its joined handle, by the thread that merges other workers'
+// stats into it. No two `alloc` calls on the same arena ever race.
+unsafe impl Sync for Arena {}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self {
+            blocks: RefCell::new(vec![Vec::with_capacity(BLOCK_SIZE)]),
+        }
+    }
+
+    /// Copies `bytes` into the arena and returns a slice borrowed from
+    /// it, valid for the lifetime of `self`.
+    pub fn alloc(&self, bytes: &[u8]) -> &[u8] {
+        let mut blocks = self.blocks.borrow_mut();
+
+        let last = blocks.last().unwrap();
+        if last.len() + bytes.len() > last.capacity() {
+            blocks.push(Vec::with_capacity(BLOCK_SIZE.max(bytes.len())));
+        }
+
+        let block = blocks.last_mut().unwrap();
+        let start = block.len();
+        block.extend_from_slice(bytes);
+
+        // SAFETY: `block`'s heap buffer is only grown in place up to its
+        // reserved capacity, and once a block is full we push a new one
+        // rather than reallocating it, so this pointer stays valid for
+        // as long as `self` (and therefore `block`) is alive. The
+        // returned slice borrows `self`, so it cannot outlive the arena.
+        unsafe { std::slice::from_raw_parts(block.as_ptr().add(start), bytes.len()) }
+    }
+
+    /// Like [`Arena::alloc`], but for station names, which are always
+    /// valid UTF-8 since they were sliced out of the input with
+    /// `from_utf8_unchecked` in the first place.
+    pub fn alloc_str(&self, s: &str) -> &str {
+        let bytes = self.alloc(s.as_bytes());
+        unsafe { std::str::from_utf8_unchecked(bytes) }
+    }
+}
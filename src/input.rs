@@ -0,0 +1,65 @@
+use std::io::{self, Read};
+
+use crate::mmap::MMappedFile;
+
+/// Size of the buffer used to pull data out of a streamed [`InputSource`].
+/// A multiple of the page size so reads line up with the OS's own
+/// buffering.
+pub const CHUNK_SIZE: usize = 1 << 20;
+
+/// Exposes measurement data to a `run` function, either as a single
+/// zero-copy view (for sources that can be memory-mapped) or as a
+/// sequence of fixed-size chunks (for pipes and other streams that
+/// can't be). Callers should check [`InputSource::as_whole`] first and
+/// only fall back to [`InputSource::read_chunk`] when it returns `None`.
+pub trait InputSource {
+    /// Returns the entire input as one contiguous slice, if the source
+    /// supports it.
+    fn as_whole(&self) -> Option<&[u8]>;
+
+    /// Fills `buf` from the stream and returns the number of bytes
+    /// actually read; fewer than `buf.len()` only at EOF. Only called
+    /// when `as_whole` returns `None`.
+    fn read_chunk(&mut self, buf: &mut [u8]) -> io::Result<usize>;
+}
+
+impl<'a> InputSource for MMappedFile<'a> {
+    fn as_whole(&self) -> Option<&[u8]> {
+        Some(self.as_slice())
+    }
+
+    fn read_chunk(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        unreachable!("MMappedFile always serves as_whole, read_chunk is never called")
+    }
+}
+
+/// Reads from any [`Read`] implementation (stdin, a pipe, ...) in
+/// page-aligned chunks, for sources that can't be memory-mapped.
+pub struct ChunkedSource<R> {
+    reader: R,
+}
+
+impl<R: Read> ChunkedSource<R> {
+    pub fn new(reader: R) -> Self {
+        Self { reader }
+    }
+}
+
+impl<R: Read> InputSource for ChunkedSource<R> {
+    fn as_whole(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn read_chunk(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // Like `Read::read_exact`, but tolerant of stopping short at EOF:
+        // loop until `buf` is full or a `read` returns 0.
+        let mut total = 0;
+        while total < buf.len() {
+            match self.reader.read(&mut buf[total..])? {
+                0 => break,
+                n => total += n,
+            }
+        }
+        Ok(total)
+    }
+}
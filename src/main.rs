@@ -2,6 +2,8 @@ use std::env;
 
 use anyhow::{anyhow, bail, Result};
 
+mod arena;
+mod input;
 mod mmap;
 
 mod naive;
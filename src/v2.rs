@@ -0,0 +1,448 @@
+use std::{cmp::min, collections::HashMap, fmt::Display, fs::File, io, io::BufRead, thread};
+
+use anyhow::Result;
+
+use crate::{
+    arena::Arena,
+    input::{ChunkedSource, InputSource, CHUNK_SIZE},
+    mmap::MMappedFile,
+};
+
+const MAX_THREADS: usize = 8;
+const PAGE_SIZE: usize = 4096;
+
+pub(crate) fn run(filename: &str) -> Result<()> {
+    if filename == "-" {
+        run_with_source(ChunkedSource::new(std::io::stdin()))
+    } else {
+        let file = unsafe { MMappedFile::new(File::open(filename)?) }?;
+        run_with_source(file)
+    }
+}
+
+fn run_with_source(mut source: impl InputSource) -> Result<()> {
+    match source.as_whole() {
+        Some(data) => run_whole(data),
+        None => run_streaming(&mut source),
+    }
+}
+
+// Fast path for sources that can hand us the entire input as one
+// zero-copy slice (currently: memory-mapped files): split it into
+// blocks and aggregate each on its own thread.
+fn run_whole(data: &[u8]) -> Result<()> {
+    let num_threads = min(MAX_THREADS, (data.len() + PAGE_SIZE - 1) / PAGE_SIZE);
+
+    let block_size = data.len() / num_threads;
+    // Round up to nearest multiple of PAGE_SIZE.
+    let block_size = ((block_size + PAGE_SIZE - 1) / PAGE_SIZE) * PAGE_SIZE;
+
+    // A scope, rather than bare `thread::spawn`, so that worker errors
+    // (as opposed to the boundary-truncation case, which is expected)
+    // come back through a joined `Result` instead of being dropped along
+    // with an unjoined `JoinHandle`.
+    let station_stats: Result<StationStats<'static>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads)
+            .map(|i| {
+                scope.spawn(move || -> Result<StationStats<'static>> {
+                    // `block_size` is rounded up to a `PAGE_SIZE` multiple,
+                    // so for the last thread or two `block_size * i` can
+                    // overshoot `data.len()`; clamp before it's used to
+                    // index or slice `data`.
+                    let block_start = min(block_size * i, data.len());
+                    let block_end = min(data.len(), block_start + block_size);
+
+                    // Walk backwards to find the start of the record potentially
+                    // straddling the boundary with the previous block.
+                    let mut record_start = block_start;
+                    while record_start > 0 && data[record_start - 1] != b'\n' {
+                        record_start -= 1;
+                    }
+                    let record_start = record_start;
+
+                    let block = &data[record_start..block_end];
+
+                    // Unless this is the very last block of the file, a
+                    // block that doesn't end in `\n` has its final record
+                    // cut off by `block_end`; that fragment is handled as
+                    // part of the next block, so detect it structurally
+                    // (not via `Measurement::try_from`'s dot-position
+                    // heuristic, which a truncated-but-long-enough prefix
+                    // like "Philadelphia;2" can slip past as `InvalidData`).
+                    let is_last_block = block_end == data.len();
+                    let truncated_tail = !is_last_block && !block.ends_with(b"\n");
+
+                    // The 1-based number of `block`'s first line within the
+                    // whole file, computed lazily: only a parse error (the
+                    // rare case) needs it, so a clean run never pays for the
+                    // scan over `data[..record_start]`.
+                    let first_line_no =
+                        || data[..record_start].iter().filter(|&&b| b == b'\n').count() + 1;
+
+                    // Leaked for the remaining lifetime of the process:
+                    // each worker's arena is cheap, and leaking sidesteps
+                    // having to thread a self-referential (arena, station
+                    // map) pair back out through `scope.spawn`.
+                    let arena: &'static Arena = Box::leak(Box::new(Arena::new()));
+                    let mut station_stats = StationStats::new(arena);
+                    process_lines(block, first_line_no, truncated_tail, &mut station_stats)?;
+                    Ok(station_stats)
+                })
+            })
+            .collect();
+
+        let mut results = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("worker thread panicked"));
+        let mut station_stats = results.next().expect("at least one worker thread")?;
+        for other in results {
+            station_stats.merge(other?);
+        }
+        Ok(station_stats)
+    });
+
+    println!("{}", station_stats?);
+
+    Ok(())
+}
+
+// Slow path for sources that can only be read sequentially (e.g. stdin):
+// pull fixed-size chunks and stitch together records that straddle a
+// chunk boundary, aggregating on the current thread.
+fn run_streaming(source: &mut impl InputSource) -> Result<()> {
+    let arena = Arena::new();
+    let mut station_stats = StationStats::new(&arena);
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut carry = Vec::new();
+    // 1-based number of the next line to be read, so a parse error can
+    // report an absolute line number across chunk boundaries.
+    let mut next_line_no = 1;
+
+    loop {
+        let n = source.read_chunk(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+
+        let mut window = std::mem::take(&mut carry);
+        window.extend_from_slice(&buf[..n]);
+
+        // Only the lines up to the last newline are complete; anything
+        // after it carries over to the next chunk.
+        let split = match window.iter().rposition(|&b| b == b'\n') {
+            Some(pos) => pos + 1,
+            None => 0,
+        };
+        carry.extend_from_slice(&window[split..]);
+        next_line_no += process_lines(
+            &window[..split],
+            || next_line_no,
+            false,
+            &mut station_stats,
+        )?;
+    }
+
+    // Unlike v3's `foreach_measurement`, which needs a trailing `\n` to
+    // recognize a value as complete, `BufRead::lines()` already yields a
+    // final line without one, so `carry` needs no synthetic newline here
+    // even if the input file itself doesn't end in one.
+    process_lines(&carry, || next_line_no, false, &mut station_stats)?;
+
+    println!("{}", station_stats);
+
+    Ok(())
+}
+
+// Processes each complete line in `data` into `station_stats`, returning
+// the number of lines processed. `first_line_no` is called, at most once,
+// only if a parse error is about to be propagated, to get the 1-based
+// number of `data`'s first line within the whole input; see
+// `v3::foreach_measurement` for why it's passed lazily. `truncated_tail`
+// marks that `data`'s last line, if there is one, is a record cut off by
+// a block boundary rather than a genuine parse target.
+fn process_lines<N>(
+    data: &[u8],
+    first_line_no: N,
+    truncated_tail: bool,
+    station_stats: &mut StationStats<'_>,
+) -> Result<usize>
+where
+    N: FnOnce() -> usize,
+{
+    let mut lines = data.lines().peekable();
+    // 1-based number of the current line within `data`; cheap to track
+    // incrementally, unlike `first_line_no`. Only counts lines actually
+    // handed to `Measurement::try_from`, not a skipped `truncated_tail`
+    // fragment, since the caller adds this count onto its own running
+    // total to number the *next* chunk's lines.
+    let mut line_no = 0;
+    while let Some(line) = lines.next() {
+        let line = line?;
+        if truncated_tail && lines.peek().is_none() {
+            break;
+        }
+        line_no += 1;
+        match Measurement::try_from(line.as_str()) {
+            Ok(measurement) => station_stats.record(measurement),
+            Err(e) => {
+                return Err(anyhow::Error::new(e)
+                    .context(format!("line {}", first_line_no() + line_no - 1)));
+            }
+        }
+    }
+
+    Ok(line_no)
+}
+
+#[derive(Debug)]
+struct Measurement<'a> {
+    station_name: &'a str,
+    // Tenths of a degree, e.g. "12.3" is stored as 123.
+    value: i16,
+}
+
+impl<'a> TryFrom<&'a str> for Measurement<'a> {
+    type Error = io::Error;
+
+    // Returns Err if s does not match the format "abcdef;[-][0]0.0". A
+    // line shorter than 4 bytes, or one with its '.' in place but no
+    // `;`, is reported as `UnexpectedEof`, since that's the shape a
+    // record straddling a block boundary takes; a line without a '.' in
+    // its second-to-last byte, or with bad digits, is reported as
+    // `InvalidData`, a genuine parse failure.
+    fn try_from(s: &'a str) -> io::Result<Self> {
+        if s.len() < 4 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated measurement",
+            ));
+        }
+        if s.as_bytes()[s.len() - 2] != b'.' {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid measurement {:?}", s),
+            ));
+        }
+
+        let Some((station_name, value)) = s.split_once(';') else {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "missing ';' separator",
+            ));
+        };
+
+        Ok(Measurement {
+            station_name,
+            value: parse_tenths(value.as_bytes())?,
+        })
+    }
+}
+
+// Parses a reading like "-12.3" or "5.4" into tenths of a degree. The
+// grammar is fixed (optional `-`, one or two integer digits, `.`, exactly
+// one fractional digit), so a single pass over the digit bytes suffices.
+fn parse_tenths(bytes: &[u8]) -> io::Result<i16> {
+    let (negative, bytes) = match bytes.first() {
+        Some(b'-') => (true, &bytes[1..]),
+        _ => (false, bytes),
+    };
+
+    let mut value: i16 = 0;
+    for &b in bytes {
+        match b {
+            b'.' => continue,
+            b'0'..=b'9' => value = value * 10 + (b - b'0') as i16,
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid digit {:#04x} in measurement value", b),
+                ))
+            }
+        }
+    }
+
+    Ok(if negative { -value } else { value })
+}
+
+struct Stats {
+    min: i16,
+    max: i16,
+    sum: i64,
+    count: u32,
+}
+
+struct StationStats<'arena> {
+    arena: &'arena Arena,
+    stats: HashMap<&'arena str, Stats>,
+}
+
+impl<'arena> StationStats<'arena> {
+    fn new(arena: &'arena Arena) -> Self {
+        Self {
+            arena,
+            stats: HashMap::new(),
+        }
+    }
+
+    fn record(&mut self, measurement: Measurement) {
+        let station_name = measurement.station_name;
+        let value = measurement.value;
+
+        if let Some(stats) = self.stats.get_mut(station_name) {
+            if value < stats.min {
+                stats.min = value
+            } else if value > stats.max {
+                stats.max = value
+            }
+            stats.sum += value as i64;
+            stats.count += 1;
+        } else {
+            let station_name = self.arena.alloc_str(station_name);
+            self.stats.insert(
+                station_name,
+                Stats {
+                    min: value,
+                    max: value,
+                    sum: value as i64,
+                    count: 1,
+                },
+            );
+        }
+    }
+
+    fn merge(&mut self, other: StationStats) {
+        for (station_name, other_stats) in other.stats.into_iter() {
+            if let Some(stats) = self.stats.get_mut(station_name) {
+                if other_stats.min < stats.min {
+                    stats.min = other_stats.min;
+                }
+                if other_stats.max > stats.max {
+                    stats.max = other_stats.max;
+                }
+                stats.sum += other_stats.sum;
+                stats.count += other_stats.count;
+            } else {
+                // `other`'s keys are borrowed from a different worker's
+                // arena, so intern them into our own before inserting.
+                let station_name = self.arena.alloc_str(station_name);
+                self.stats.insert(station_name, other_stats);
+            }
+        }
+    }
+}
+
+impl<'arena> Display for StationStats<'arena> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut stations: Vec<_> = self.stats.keys().collect();
+        stations.sort();
+        let num_stations = stations.len();
+
+        write!(f, "{{")?;
+        for (idx, station) in stations.into_iter().enumerate() {
+            let stats = self.stats.get(station).unwrap();
+            let mean = stats.sum as f64 / (stats.count as f64) / 10.0;
+            write!(
+                f,
+                "{}={:.1}/{:.1}/{:.1}",
+                station,
+                stats.min as f64 / 10.0,
+                mean,
+                stats.max as f64 / 10.0
+            )?;
+
+            if idx != num_stations - 1 {
+                write!(f, ", ")?
+            }
+        }
+        write!(f, "}}")?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(data: &[u8], first_line_no: usize, truncated_tail: bool) -> Result<usize> {
+        let arena = Arena::new();
+        let mut station_stats = StationStats::new(&arena);
+        process_lines(data, || first_line_no, truncated_tail, &mut station_stats)
+    }
+
+    #[test]
+    fn read_measurements_end_newline() {
+        let data: &[u8] = r#"Dushanbe;1.7
+Honiara;34.9
+Taipei;3.3
+Suwałki;4.2
+Lahore;15.3
+Philadelphia;24.4
+Kingston;29.0
+Hamburg;-18.1
+Damascus;5.4
+Rabat;16.6
+"#
+        .as_bytes();
+
+        assert_eq!(process(data, 1, false).unwrap(), 10);
+    }
+
+    #[test]
+    fn read_measurements_no_newline() {
+        let data: &[u8] = r#"Dushanbe;1.7
+Honiara;34.9
+Taipei;3.3
+Suwałki;4.2
+Lahore;15.3
+Philadelphia;24.4
+Kingston;29.0
+Hamburg;-18.1
+Damascus;5.4
+Rabat;16.6"#
+            .as_bytes();
+
+        assert_eq!(process(data, 1, false).unwrap(), 10);
+    }
+
+    #[test]
+    fn truncated_tail_is_dropped_not_parsed() {
+        // The last line is a block-boundary fragment, not a genuine
+        // parse target; `truncated_tail` must make `process_lines` skip
+        // it instead of reporting it as an error ("Taipei;1" fails
+        // `Measurement::try_from`'s dot check as `InvalidData`).
+        let data: &[u8] = b"Dushanbe;1.7\nHoniara;34.9\nTaipei;1";
+
+        assert_eq!(process(data, 1, true).unwrap(), 2);
+    }
+
+    #[test]
+    fn invalid_digit_is_reported_as_invalid_data() {
+        let data: &[u8] = b"Dushanbe;1x2.3\n";
+        let err = process(data, 1, false).unwrap_err();
+        let io_err = err.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn missing_dot_is_reported_as_invalid_data() {
+        // No '.' at all, as opposed to an unexpected byte: still the
+        // wrong shape, not a truncated fragment.
+        let data: &[u8] = b"Dushanbe;99999\n";
+        let err = process(data, 1, false).unwrap_err();
+        let io_err = err.downcast_ref::<io::Error>().unwrap();
+        assert_eq!(io_err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_error_reports_absolute_line_number() {
+        let data: &[u8] = b"Dushanbe;1.7\nHoniara;34.9\nTaipei;x.x\n";
+        let err = process(data, 10, false).unwrap_err();
+        assert!(
+            err.to_string().contains("line 12"),
+            "expected error to reference line 12 (block starts at line 10, bad line is its 3rd), got: {}",
+            err
+        );
+    }
+}
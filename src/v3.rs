@@ -1,68 +1,162 @@
 use std::{
-    cmp::min, collections::HashMap, fmt::Display, fs::File, io::BufRead, path::Path, sync::mpsc,
+    cmp::min,
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead},
+    thread,
 };
 
 use anyhow::Result;
+use fxhash::FxHashMap as HashMap;
+use memchr::{memchr, memchr_iter, memrchr};
 
-use crate::mmap::MMappedFile;
+use crate::{
+    arena::Arena,
+    input::{ChunkedSource, InputSource, CHUNK_SIZE},
+    mmap::MMappedFile,
+};
 
 const MAX_THREADS: usize = 8;
 const PAGE_SIZE: usize = 4096;
 
-pub(crate) fn run<P>(filename: P) -> Result<()>
-where
-    P: AsRef<Path>,
-{
-    let file = unsafe { MMappedFile::new(File::open(filename)?) }?;
-    let data = file.as_slice();
+pub(crate) fn run(filename: &str) -> Result<()> {
+    if filename == "-" {
+        run_with_source(ChunkedSource::new(std::io::stdin()))
+    } else {
+        let file = unsafe { MMappedFile::new(File::open(filename)?) }?;
+        run_with_source(file)
+    }
+}
 
+fn run_with_source(mut source: impl InputSource) -> Result<()> {
+    match source.as_whole() {
+        Some(data) => run_whole(data),
+        None => run_streaming(&mut source),
+    }
+}
+
+// Fast path for sources that can hand us the entire input as one
+// zero-copy slice (currently: memory-mapped files): split it into
+// blocks and aggregate each on its own thread.
+fn run_whole(data: &[u8]) -> Result<()> {
     let num_threads = min(MAX_THREADS, (data.len() + PAGE_SIZE - 1) / PAGE_SIZE);
 
     let block_size = data.len() / num_threads;
     // Round up to nearest multiple of PAGE_SIZE.
     let block_size = ((block_size + PAGE_SIZE - 1) / PAGE_SIZE) * PAGE_SIZE;
 
-    let (results_tx, results_rx) = mpsc::channel();
+    // A scope, rather than bare `thread::spawn`, because `data` borrows
+    // from whatever `InputSource` produced it rather than being
+    // unconditionally `'static`.
+    let station_stats: Result<StationStats<'static>> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..num_threads)
+            .map(|i| {
+                scope.spawn(move || -> Result<StationStats<'static>> {
+                    // `block_size` is rounded up to a `PAGE_SIZE` multiple,
+                    // so for the last thread or two `block_size * i` can
+                    // overshoot `data.len()`; clamp before it's used to
+                    // index or slice `data`.
+                    let block_start = min(block_size * i, data.len());
+                    let block_end = min(data.len(), block_start + block_size);
 
-    for i in 0..num_threads {
-        let results_tx = results_tx.clone();
-        std::thread::spawn(move || -> Result<()> {
-            let block_start = block_size * i;
-            let block_end = min(data.len(), block_start + block_size);
+                    // Walk backwards to find the start of the record
+                    // potentially straddling the boundary with the
+                    // previous block.
+                    let record_start = match memrchr(b'\n', &data[..block_start]) {
+                        Some(pos) => pos + 1,
+                        None => 0,
+                    };
 
-            // Walk backwards to find the start of the record potentially
-            // straddling the boundary with the previous block.
-            let mut record_start = block_start;
-            while record_start > 0 && data[record_start - 1] != b'\n' {
-                record_start -= 1;
-            }
-            let record_start = record_start;
-
-            let data = &data[record_start..block_end];
-            let lines = data.lines();
-
-            let mut station_stats = StationStats::new();
-            for line in lines {
-                let line = line?;
-                match Measurement::try_from(line.as_str()) {
-                    Ok(measurement) => station_stats.record(measurement),
-                    // If we failed to parse the current line as a Measurement,
-                    // then we assume that it was truncated at the block boundary,
-                    // and has therefore already been handled as part of the next block.
-                    Err(_) => break,
-                }
-            }
+                    let block = &data[record_start..block_end];
 
-            results_tx.send(station_stats).unwrap();
-            Ok(())
-        });
+                    // Leaked for the remaining lifetime of the process:
+                    // each worker's arena is cheap, and leaking sidesteps
+                    // having to thread a self-referential (arena, station
+                    // map) pair back out through `scope.spawn`.
+                    let arena: &'static Arena = Box::leak(Box::new(Arena::new()));
+                    let mut station_stats = StationStats::new(arena);
+                    // The 1-based number of `block`'s first record within
+                    // the whole file, computed lazily: only a parse error
+                    // (the rare case) needs it, so a clean run never pays
+                    // for the scan over `data[..record_start]`.
+                    let first_record_no = || memchr_iter(b'\n', &data[..record_start]).count() + 1;
+
+                    // `foreach_measurement` needs a trailing `\n` to
+                    // recognize the last value as complete rather than a
+                    // boundary-truncated fragment to discard; the last
+                    // block of the whole file may not have one (the input
+                    // file itself need not end in a newline), so patch
+                    // one on, same as `run_streaming` does for its final
+                    // fragment.
+                    if block_end == data.len() && !block.ends_with(b"\n") {
+                        let mut owned = block.to_vec();
+                        owned.push(b'\n');
+                        foreach_measurement(&owned, first_record_no, |m| station_stats.record(m))?;
+                    } else {
+                        foreach_measurement(block, first_record_no, |m| station_stats.record(m))?;
+                    }
+                    Ok(station_stats)
+                })
+            })
+            .collect();
+
+        let mut results = handles
+            .into_iter()
+            .map(|handle| handle.join().expect("worker thread panicked"));
+        let mut station_stats = results.next().expect("at least one worker thread")?;
+        for other in results {
+            station_stats.merge(other?);
+        }
+        Ok(station_stats)
+    });
+
+    println!("{}", station_stats?);
+
+    Ok(())
+}
+
+// Slow path for sources that can only be read sequentially (e.g. stdin):
+// pull fixed-size chunks and stitch together records that straddle a
+// chunk boundary, aggregating on the current thread.
+fn run_streaming(source: &mut impl InputSource) -> Result<()> {
+    let arena = Arena::new();
+    let mut station_stats = StationStats::new(&arena);
+
+    let mut buf = vec![0u8; CHUNK_SIZE];
+    let mut carry = Vec::new();
+    // 1-based number of the next record to be read, so a parse error can
+    // report an absolute record number across chunk boundaries.
+    let mut next_record_no = 1;
+
+    loop {
+        let n = source.read_chunk(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+
+        let mut window = std::mem::take(&mut carry);
+        window.extend_from_slice(&buf[..n]);
+
+        // Only the records up to the last newline are complete; anything
+        // after it carries over to the next chunk.
+        let split = match memrchr(b'\n', &window) {
+            Some(pos) => pos + 1,
+            None => 0,
+        };
+        carry.extend_from_slice(&window[split..]);
+        next_record_no += foreach_measurement(&window[..split], || next_record_no, |m| {
+            station_stats.record(m)
+        })?;
     }
-    drop(results_tx);
 
-    let mut station_stats = results_rx.recv().unwrap();
-    while let Ok(new_station_stats) = results_rx.recv() {
-        station_stats.merge(new_station_stats);
+    // The very last record may not end in a newline, but
+    // `foreach_measurement` needs one to recognize the value as complete
+    // rather than a boundary-truncated fragment to discard; append a
+    // synthetic one so it isn't silently dropped.
+    if !carry.is_empty() && !carry.ends_with(b"\n") {
+        carry.push(b'\n');
     }
+    foreach_measurement(&carry, || next_record_no, |m| station_stats.record(m))?;
 
     println!("{}", station_stats);
 
@@ -72,44 +166,227 @@ where
 #[derive(Debug)]
 struct Measurement<'a> {
     station_name: &'a str,
-    value: f32,
+    // Tenths of a degree, e.g. "12.3" is stored as 123.
+    value: i16,
 }
 
-impl<'a> TryFrom<&'a str> for Measurement<'a> {
-    type Error = ();
-
-    // Returns Err if s does not match the format "abcdef;[-][0]0.0"
-    fn try_from(s: &'a str) -> std::result::Result<Self, ()> {
-        if s.len() < 4 || s.as_bytes()[s.len() - 2] != b'.' {
-            return Err(());
-        }
+// Calls `visit` for each complete measurement record in `data`, returning
+// the number of records processed. `first_record_no` is called, at most
+// once, only if a parse error is about to be propagated, to get the
+// 1-based number of `data`'s first record within the whole input; it's
+// passed lazily since computing it (typically by scanning everything
+// before `data`) would otherwise cost every block something on every
+// successful run, for a number that's only ever used to fail loudly with
+// a record number instead of silently returning a wrong answer.
+fn foreach_measurement<F, N>(mut data: &[u8], first_record_no: N, mut visit: F) -> io::Result<usize>
+where
+    F: FnMut(Measurement),
+    N: FnOnce() -> usize,
+{
+    let mut n = 0;
+    while !data.is_empty() {
+        let Some(station_name_len) = memchr(b';', data) else {
+            break;
+        };
+        let station_name = unsafe { std::str::from_utf8_unchecked(&data[0..station_name_len]) };
+        data.consume(station_name_len + 1);
 
-        let Some((station_name, value)) = s.split_once(';') else {
-            return Err(());
+        let Some(value_len) = memchr(b'\n', data) else {
+            break;
+        };
+        // Pass the full remaining slice, not `&data[0..value_len]`, so the
+        // SWAR path below has its 8-byte window even though the value
+        // itself is only 3-5 bytes long; it falls back to a scalar parse
+        // of just the value bytes when that window isn't available.
+        let value = match parse_tenths(data, value_len) {
+            Ok(value) => value,
+            // A record truncated at the block boundary is expected to be
+            // the last one of a block; it's already handled as part of
+            // the next one.
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => {
+                return Err(io::Error::new(
+                    e.kind(),
+                    format!("record {}: {}", first_record_no() + n, e),
+                ))
+            }
         };
+        data.consume(value_len + 1);
+        n += 1;
 
-        Ok(Measurement {
+        visit(Measurement {
             station_name,
-            value: value.parse().unwrap(), // Panic since this is unexpected.
-        })
+            value,
+        });
+    }
+
+    Ok(n)
+}
+
+// Parses a reading like "-12.3" or "5.4" into tenths of a degree, using a
+// branchless SWAR decode when at least 8 bytes are available at `bytes`
+// (the SWAR path ignores anything past the value itself) and falling
+// back to a scalar parse of `bytes[..value_len]` for the final, possibly
+// boundary-straddling, record of a block.
+fn parse_tenths(bytes: &[u8], value_len: usize) -> io::Result<i16> {
+    if bytes.len() >= 8 {
+        parse_tenths_swar(bytes, value_len)
+    } else {
+        parse_tenths_scalar(&bytes[..value_len])
+    }
+}
+
+fn parse_tenths_scalar(bytes: &[u8]) -> io::Result<i16> {
+    if bytes.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated measurement value",
+        ));
+    }
+
+    let (negative, bytes) = match bytes.first() {
+        Some(b'-') => (true, &bytes[1..]),
+        _ => (false, bytes),
+    };
+
+    let mut seen_dot = false;
+    let mut value: i16 = 0;
+    for &b in bytes {
+        match b {
+            b'.' if !seen_dot => seen_dot = true,
+            b'0'..=b'9' => {
+                // `checked_*`, not a plain `value * 10 + ...`: a corrupt,
+                // dot-less run of digits (or just an implausibly long
+                // one) must come back as `InvalidData`, not overflow the
+                // accumulator and panic.
+                value = value
+                    .checked_mul(10)
+                    .and_then(|v| v.checked_add((b - b'0') as i16))
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "measurement value out of range",
+                        )
+                    })?;
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid digit {:#04x} in measurement value", b),
+                ))
+            }
+        }
+    }
+    if !seen_dot {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "measurement value missing '.'",
+        ));
+    }
+
+    Ok(if negative { -value } else { value })
+}
+
+// Like `parse_tenths_scalar`, but only classifies bytes instead of also
+// accumulating a value, for callers (the SWAR path below) that already
+// have their own way of turning valid bytes into a value and just need
+// to know whether `bytes` is safe to feed it.
+fn validate_tenths(bytes: &[u8]) -> io::Result<()> {
+    if bytes.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "truncated measurement value",
+        ));
+    }
+
+    let bytes = match bytes.first() {
+        Some(b'-') => &bytes[1..],
+        _ => bytes,
+    };
+
+    let mut seen_dot = false;
+    for &b in bytes {
+        match b {
+            b'.' if !seen_dot => seen_dot = true,
+            b'0'..=b'9' => {}
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid digit {:#04x} in measurement value", b),
+                ))
+            }
+        }
+    }
+    if !seen_dot {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "measurement value missing '.'",
+        ));
     }
+
+    Ok(())
+}
+
+// The grammar is fixed: optional `-`, one or two integer digits, `.`,
+// exactly one fractional digit, so the value always fits in the first 8
+// bytes at `bytes`. Every digit byte (`0x30..=0x39`) has bit 0x10 set,
+// while `.` (0x2E) does not, so locating the cleared bit among the
+// candidate positions locates the decimal point and therefore the digit
+// layout, without a single branch.
+//
+// The decode itself is branchless and would happily turn garbage bytes
+// into a wrong-but-plausible-looking value, so `bytes[..value_len]` is
+// validated first by `validate_tenths`, which only classifies bytes
+// (digit, `.`, or leading `-`) rather than also accumulating a value
+// like `parse_tenths_scalar` does; that keeps this path's failure
+// behavior (`UnexpectedEof`/`InvalidData`) identical to the scalar
+// fallback's for a record that happens to land in the middle of a block
+// rather than at its boundary, without paying for a full second parse of
+// every value on the hot path.
+fn parse_tenths_swar(bytes: &[u8], value_len: usize) -> io::Result<i16> {
+    validate_tenths(&bytes[..value_len])?;
+
+    // `validate_tenths` doesn't check the shape, so a value outside the
+    // fixed grammar (e.g. "123.4" or "1.23") goes to the scalar parse
+    // instead of the decode below, which only handles that grammar.
+    let unsigned_len = value_len - (bytes[0] == b'-') as usize;
+    if !(3..=4).contains(&unsigned_len) || bytes[value_len - 2] != b'.' {
+        return parse_tenths_scalar(&bytes[..value_len]);
+    }
+
+    let word = u64::from_le_bytes(bytes[..8].try_into().unwrap());
+
+    let decimal_sep_pos = (!word & 0x10101000).trailing_zeros();
+    let shift = 28 - decimal_sep_pos;
+
+    // All 64 bits set if the first byte is '-', 0 otherwise.
+    let sign = ((!word << 59) as i64 >> 63) as u64;
+    let design_mask = !(sign & 0xFF);
+
+    let digits = ((word & design_mask) << shift) & 0x0F000F0F00;
+    let abs_value = (digits.wrapping_mul(0x640a0001) >> 32) & 0x3FF;
+    let value = (abs_value ^ sign).wrapping_sub(sign);
+
+    Ok(value as i16)
 }
 
 struct Stats {
-    min: f32,
-    max: f32,
-    sum: f32,
+    min: i16,
+    max: i16,
+    sum: i64,
     count: u32,
 }
 
-struct StationStats {
-    stats: HashMap<String, Stats>,
+struct StationStats<'arena> {
+    arena: &'arena Arena,
+    stats: HashMap<&'arena str, Stats>,
 }
 
-impl StationStats {
-    fn new() -> Self {
+impl<'arena> StationStats<'arena> {
+    fn new(arena: &'arena Arena) -> Self {
         Self {
-            stats: HashMap::new(),
+            arena,
+            stats: HashMap::default(),
         }
     }
 
@@ -123,15 +400,16 @@ impl StationStats {
             } else if value > stats.max {
                 stats.max = value
             }
-            stats.sum += value;
+            stats.sum += value as i64;
             stats.count += 1;
         } else {
+            let station_name = self.arena.alloc_str(station_name);
             self.stats.insert(
-                station_name.to_owned(),
+                station_name,
                 Stats {
                     min: value,
                     max: value,
-                    sum: value,
+                    sum: value as i64,
                     count: 1,
                 },
             );
@@ -140,7 +418,7 @@ impl StationStats {
 
     fn merge(&mut self, other: StationStats) {
         for (station_name, other_stats) in other.stats.into_iter() {
-            if let Some(stats) = self.stats.get_mut(&station_name) {
+            if let Some(stats) = self.stats.get_mut(station_name) {
                 if other_stats.min < stats.min {
                     stats.min = other_stats.min;
                 }
@@ -150,13 +428,16 @@ impl StationStats {
                 stats.sum += other_stats.sum;
                 stats.count += other_stats.count;
             } else {
+                // `other`'s keys are borrowed from a different worker's
+                // arena, so intern them into our own before inserting.
+                let station_name = self.arena.alloc_str(station_name);
                 self.stats.insert(station_name, other_stats);
             }
         }
     }
 }
 
-impl Display for StationStats {
+impl<'arena> Display for StationStats<'arena> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut stations: Vec<_> = self.stats.keys().collect();
         stations.sort();
@@ -165,11 +446,14 @@ impl Display for StationStats {
         write!(f, "{{")?;
         for (idx, station) in stations.into_iter().enumerate() {
             let stats = self.stats.get(station).unwrap();
-            let mean = stats.sum / (stats.count as f32);
+            let mean = stats.sum as f64 / (stats.count as f64) / 10.0;
             write!(
                 f,
                 "{}={:.1}/{:.1}/{:.1}",
-                station, stats.min, mean, stats.max
+                station,
+                stats.min as f64 / 10.0,
+                mean,
+                stats.max as f64 / 10.0
             )?;
 
             if idx != num_stations - 1 {
@@ -181,3 +465,114 @@ impl Display for StationStats {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_measurements_end_newline() {
+        let data: &[u8] = r#"Dushanbe;1.7
+Honiara;34.9
+Taipei;3.3
+Suwałki;4.2
+Lahore;15.3
+Philadelphia;24.4
+Kingston;29.0
+Hamburg;-18.1
+Damascus;5.4
+Rabat;16.6
+"#
+        .as_bytes();
+
+        foreach_measurement(data, || 1, |m| println!("{:?}", m)).unwrap();
+    }
+
+    #[test]
+    fn read_measurements_truncated() {
+        let data: &[u8] = r#"Dushanbe;1.7
+Honiara;34.9
+Taipei;3.3
+Suwałki;4.2
+Lahore;15.3
+Philadelphia;24.4
+Kingston;29.0
+Hamburg;-18.1
+Damascus;5.4
+Rabat;1"#
+            .as_bytes();
+        foreach_measurement(data, || 1, |m| println!("{:?}", m)).unwrap();
+    }
+
+    #[test]
+    fn read_measurements_no_newline() {
+        let data: &[u8] = r#"Dushanbe;1.7
+Honiara;34.9
+Taipei;3.3
+Suwałki;4.2
+Lahore;15.3
+Philadelphia;24.4
+Kingston;29.0
+Hamburg;-18.1
+Damascus;5.4
+Rabat;16.6"#
+            .as_bytes();
+        foreach_measurement(data, || 1, |m| println!("{:?}", m)).unwrap();
+    }
+
+    #[test]
+    fn invalid_digit_is_reported_as_invalid_data() {
+        // "1x2.3" is the wrong shape, not a boundary-truncated fragment,
+        // so this must come back as `InvalidData`, not `UnexpectedEof`.
+        let data: &[u8] = b"Dushanbe;1x2.3\n";
+        let err = foreach_measurement(data, || 1, |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn invalid_digit_is_reported_as_invalid_data_on_swar_path() {
+        // Padded with trailing records so `bytes.len() >= 8` at the bad
+        // value, putting the error through `parse_tenths_swar` (and its
+        // `validate_tenths` check) rather than the scalar fallback.
+        let data: &[u8] = b"Dushanbe;1x2.3\nHonoira;34.9\nTaipei;3.3\n";
+        let err = foreach_measurement(data, || 1, |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn dotless_value_is_reported_as_invalid_data_on_scalar_path() {
+        // All digits, no '.': the wrong shape, not merely an unexpected
+        // byte, and short enough (< 8 remaining bytes) to hit
+        // `parse_tenths_scalar` rather than the SWAR decode.
+        let data: &[u8] = b"Dushanbe;99999\n";
+        let err = foreach_measurement(data, || 1, |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn dotless_value_is_reported_as_invalid_data_on_swar_path() {
+        // Padded with trailing records so `bytes.len() >= 8` at the
+        // dot-less value, putting it through `parse_tenths_swar`, whose
+        // `decimal_sep_pos` bit search has no match when there's no '.'
+        // to find; `validate_tenths` must catch this before that shift
+        // runs, not let it underflow or wrap into a bogus value.
+        let data: &[u8] = b"Dushanbe;99999999\nHoniara;34.9\nTaipei;3.3\n";
+        let err = foreach_measurement(data, || 1, |_| {}).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn parse_error_reports_absolute_record_number() {
+        // `first_record_no` stands in for the 1-based number of this
+        // block's first record within the whole input; the error should
+        // add the in-block record index to it, not report the in-block
+        // index alone.
+        let data: &[u8] = b"Dushanbe;1.7\nHoniara;34.9\nTaipei;x.x\n";
+        let err = foreach_measurement(data, || 10, |_| {}).unwrap_err();
+        assert!(
+            err.to_string().contains("record 12"),
+            "expected error to reference record 12 (block starts at record 10, bad record is its 3rd), got: {}",
+            err
+        );
+    }
+}